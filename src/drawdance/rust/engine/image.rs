@@ -1,15 +1,21 @@
 use crate::{
-    dp_error_anyhow, DP_CanvasState, DP_Image, DP_ImageScaleInterpolation, DP_Output, DP_UPixel8,
+    dp_error_anyhow, DP_CanvasState, DP_Image, DP_Image16, DP_ImageScaleInterpolation, DP_Input,
+    DP_Output, DP_UPixel16, DP_UPixel8, DP_blend_color16_background, DP_blend_color16_to,
     DP_blend_color8_background, DP_blend_color8_to, DP_canvas_state_to_flat_image,
-    DP_file_output_new_from_path, DP_image_free, DP_image_height, DP_image_new,
-    DP_image_new_subimage, DP_image_pixels, DP_image_scale_pixels, DP_image_width,
-    DP_image_write_jpeg, DP_image_write_png, DP_image_write_qoi, DP_image_write_webp,
-    DP_output_free, DP_FLAT_IMAGE_RENDER_FLAGS,
+    DP_canvas_state_to_flat_image_region, DP_file_input_new_from_path,
+    DP_file_output_new_from_path, DP_image16_free, DP_image16_height, DP_image16_new,
+    DP_image16_new_from_image8, DP_image16_pixels, DP_image16_to_image8, DP_image16_width,
+    DP_image_free, DP_image_height, DP_image_new, DP_image_new_from_jpeg, DP_image_new_from_png,
+    DP_image_new_from_qoi, DP_image_new_from_webp, DP_image_new_subimage, DP_image_pixels,
+    DP_image_scale_pixels, DP_image_width, DP_image_write_jpeg, DP_image_write_png,
+    DP_image_write_qoi, DP_image_write_webp, DP_input_free, DP_mem_input_new, DP_mem_output_data,
+    DP_mem_output_new, DP_mem_output_size, DP_output_free, DP_output_new_fn,
+    DP_FLAT_IMAGE_RENDER_FLAGS,
 };
 use anyhow::{anyhow, Result};
 use core::slice;
 use std::{
-    ffi::{c_int, CString},
+    ffi::{c_int, c_void, CString},
     io::{self},
     mem::size_of,
     ptr::{copy_nonoverlapping, null},
@@ -138,6 +144,100 @@ impl Image {
         }
     }
 
+    pub fn new_from_canvas_state_region(
+        cs: *mut DP_CanvasState,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<Self> {
+        let image = unsafe {
+            DP_canvas_state_to_flat_image_region(
+                cs,
+                c_int::try_from(x)?,
+                c_int::try_from(y)?,
+                c_int::try_from(width)?,
+                c_int::try_from(height)?,
+                DP_FLAT_IMAGE_RENDER_FLAGS,
+                null(),
+                null(),
+            )
+        };
+        if image.is_null() {
+            Err(dp_error_anyhow())
+        } else {
+            Ok(Image { image })
+        }
+    }
+
+    pub fn read_png(path: &str) -> Result<Self> {
+        Self::read_from_path(path, DP_image_new_from_png)
+    }
+
+    pub fn read_jpeg(path: &str) -> Result<Self> {
+        Self::read_from_path(path, DP_image_new_from_jpeg)
+    }
+
+    pub fn read_qoi(path: &str) -> Result<Self> {
+        Self::read_from_path(path, DP_image_new_from_qoi)
+    }
+
+    pub fn read_webp(path: &str) -> Result<Self> {
+        Self::read_from_path(path, DP_image_new_from_webp)
+    }
+
+    pub fn read(path: &str) -> Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let func = Self::sniff(bytes).ok_or_else(|| anyhow!("Unrecognized image format"))?;
+        let input = unsafe { DP_mem_input_new(bytes.as_ptr(), bytes.len()) };
+        if input.is_null() {
+            return Err(dp_error_anyhow());
+        }
+        Self::from_input(input, func)
+    }
+
+    fn sniff(bytes: &[u8]) -> Option<unsafe extern "C" fn(*mut DP_Input) -> *mut DP_Image> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(DP_image_new_from_png)
+        } else if bytes.starts_with(b"qoif") {
+            Some(DP_image_new_from_qoi)
+        } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(DP_image_new_from_webp)
+        } else if bytes.len() >= 3 && bytes[0] == 0xff && bytes[1] == 0xd8 && bytes[2] == 0xff {
+            Some(DP_image_new_from_jpeg)
+        } else {
+            None
+        }
+    }
+
+    fn read_from_path(
+        path: &str,
+        func: unsafe extern "C" fn(*mut DP_Input) -> *mut DP_Image,
+    ) -> Result<Self> {
+        let cpath = CString::new(path)?;
+        let input = unsafe { DP_file_input_new_from_path(cpath.as_ptr()) };
+        if input.is_null() {
+            return Err(dp_error_anyhow());
+        }
+        Self::from_input(input, func)
+    }
+
+    fn from_input(
+        input: *mut DP_Input,
+        func: unsafe extern "C" fn(*mut DP_Input) -> *mut DP_Image,
+    ) -> Result<Self> {
+        let image = unsafe { func(input) };
+        unsafe { DP_input_free(input) };
+        if image.is_null() {
+            Err(dp_error_anyhow())
+        } else {
+            Ok(Image { image })
+        }
+    }
+
     pub fn scaled(
         &self,
         scale_width: usize,
@@ -175,6 +275,125 @@ impl Image {
         }
     }
 
+    pub fn bitmap_eq(&self, other: &Image, tolerance: f64) -> bool {
+        let width = self.width();
+        let height = self.height();
+        if width != other.width() || height != other.height() {
+            return false;
+        }
+        let threshold = Self::tolerance_threshold(tolerance);
+        let a = self.pixels();
+        let b = other.pixels();
+        (0..width * height).all(|i| Self::pixel_diff(a[i], b[i]) <= threshold)
+    }
+
+    pub fn find_bitmap(&self, needle: &Image, tolerance: f64) -> Option<(usize, usize)> {
+        let threshold = Self::tolerance_threshold(tolerance);
+        self.bitmap_origins(needle, threshold).next()
+    }
+
+    pub fn count_of_bitmap(&self, needle: &Image, tolerance: f64) -> usize {
+        let threshold = Self::tolerance_threshold(tolerance);
+        self.bitmap_origins(needle, threshold).count()
+    }
+
+    fn bitmap_origins<'a>(
+        &'a self,
+        needle: &'a Image,
+        threshold: u8,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let width = self.width();
+        let height = self.height();
+        let nw = needle.width();
+        let nh = needle.height();
+        let max_y = if nh == 0 || nh > height { 0 } else { height - nh + 1 };
+        let max_x = if nw == 0 || nw > width { 0 } else { width - nw + 1 };
+        (0..max_y)
+            .flat_map(move |y| (0..max_x).map(move |x| (x, y)))
+            .filter(move |&(x, y)| self.bitmap_matches_at(needle, x, y, threshold))
+    }
+
+    fn bitmap_matches_at(&self, needle: &Image, x: usize, y: usize, threshold: u8) -> bool {
+        let width = self.width();
+        let nw = needle.width();
+        let nh = needle.height();
+        let haystack = self.pixels();
+        let needle_pixels = needle.pixels();
+        for ny in 0..nh {
+            for nx in 0..nw {
+                let hay = haystack[(x + nx) + (y + ny) * width];
+                let pix = needle_pixels[nx + ny * nw];
+                if Self::pixel_diff(hay, pix) > threshold {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn tolerance_threshold(tolerance: f64) -> u8 {
+        (tolerance * 255.0) as u8
+    }
+
+    fn pixel_diff(a: u32, b: u32) -> u8 {
+        let a = a.to_ne_bytes();
+        let b = b.to_ne_bytes();
+        let mut max_diff = 0u8;
+        for i in 0..4 {
+            let diff = (a[i] as i16 - b[i] as i16).unsigned_abs() as u8;
+            if diff > max_diff {
+                max_diff = diff;
+            }
+        }
+        max_diff
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Result<DP_UPixel8> {
+        if x < self.width() && y < self.height() {
+            Ok(DP_UPixel8 {
+                color: self.pixels()[x + y * self.width()],
+            })
+        } else {
+            Err(anyhow!("Pixel coordinates out of bounds"))
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: DP_UPixel8) -> Result<()> {
+        let width = self.width();
+        let height = self.height();
+        if x < width && y < height {
+            self.pixels_mut()[x + y * width] = unsafe { pixel.color };
+            Ok(())
+        } else {
+            Err(anyhow!("Pixel coordinates out of bounds"))
+        }
+    }
+
+    pub fn map_pixels(&mut self, f: impl Fn(DP_UPixel8) -> DP_UPixel8) {
+        for p in self.pixels_mut() {
+            *p = unsafe { f(DP_UPixel8 { color: *p }).color };
+        }
+    }
+
+    pub fn to_grayscale(&mut self) {
+        self.map_pixels(|pixel| {
+            let [r, g, b, a] = unsafe { pixel.color }.to_ne_bytes();
+            let luma = (0.21 * r as f64 + 0.72 * g as f64 + 0.07 * b as f64).round() as u8;
+            DP_UPixel8 {
+                color: u32::from_ne_bytes([luma, luma, luma, a]),
+            }
+        });
+    }
+
+    fn pixels_mut(&mut self) -> &mut [u32] {
+        unsafe {
+            slice::from_raw_parts_mut(
+                DP_image_pixels(self.image).cast(),
+                self.width() * self.height(),
+            )
+        }
+    }
+
     pub fn cropped(&self, x: usize, y: usize, width: usize, height: usize) -> Result<Image> {
         let subimg = unsafe {
             DP_image_new_subimage(
@@ -214,6 +433,82 @@ impl Image {
         self.write(path, DP_image_write_webp)
     }
 
+    pub fn encode_png(&self) -> Result<Vec<u8>> {
+        self.encode(DP_image_write_png)
+    }
+
+    pub fn encode_jpeg(&self) -> Result<Vec<u8>> {
+        self.encode(DP_image_write_jpeg)
+    }
+
+    pub fn encode_qoi(&self) -> Result<Vec<u8>> {
+        self.encode(DP_image_write_qoi)
+    }
+
+    pub fn encode_webp(&self) -> Result<Vec<u8>> {
+        self.encode(DP_image_write_webp)
+    }
+
+    pub fn write_png_to(&self, w: &mut dyn io::Write) -> Result<()> {
+        self.write_stream(w, DP_image_write_png)
+    }
+
+    pub fn write_jpeg_to(&self, w: &mut dyn io::Write) -> Result<()> {
+        self.write_stream(w, DP_image_write_jpeg)
+    }
+
+    pub fn write_qoi_to(&self, w: &mut dyn io::Write) -> Result<()> {
+        self.write_stream(w, DP_image_write_qoi)
+    }
+
+    pub fn write_webp_to(&self, w: &mut dyn io::Write) -> Result<()> {
+        self.write_stream(w, DP_image_write_webp)
+    }
+
+    fn encode(
+        &self,
+        func: unsafe extern "C" fn(*mut DP_Image, *mut DP_Output) -> bool,
+    ) -> Result<Vec<u8>> {
+        let output = unsafe { DP_mem_output_new() };
+        if output.is_null() {
+            return Err(dp_error_anyhow());
+        }
+        let result = if unsafe { func(self.image, output) } {
+            let size = unsafe { DP_mem_output_size(output) };
+            let data = unsafe { DP_mem_output_data(output) };
+            Ok(unsafe { slice::from_raw_parts(data.cast::<u8>(), size) }.to_vec())
+        } else {
+            Err(dp_error_anyhow())
+        };
+        unsafe { DP_output_free(output) };
+        result
+    }
+
+    fn write_stream(
+        &self,
+        w: &mut dyn io::Write,
+        func: unsafe extern "C" fn(*mut DP_Image, *mut DP_Output) -> bool,
+    ) -> Result<()> {
+        let mut writer: &mut dyn io::Write = w;
+        let output = unsafe {
+            DP_output_new_fn(
+                (&mut writer as *mut &mut dyn io::Write).cast::<c_void>(),
+                Some(write_stream_callback),
+                None,
+            )
+        };
+        if output.is_null() {
+            return Err(dp_error_anyhow());
+        }
+        let result = if unsafe { func(self.image, output) } {
+            Ok(())
+        } else {
+            Err(dp_error_anyhow())
+        };
+        unsafe { DP_output_free(output) };
+        result
+    }
+
     fn write(
         &self,
         path: &str,
@@ -269,3 +564,279 @@ impl Drop for Image {
         unsafe { DP_image_free(self.image) }
     }
 }
+
+unsafe extern "C" fn write_stream_callback(
+    context: *mut c_void,
+    data: *const c_void,
+    size: usize,
+) -> bool {
+    let writer = &mut *context.cast::<&mut dyn io::Write>();
+    let buf = slice::from_raw_parts(data.cast::<u8>(), size);
+    writer.write_all(buf).is_ok()
+}
+
+pub struct Image16 {
+    image: *mut DP_Image16,
+}
+
+impl Image16 {
+    pub fn new(width: usize, height: usize) -> Result<Self> {
+        if width > 0 && height > 0 {
+            let w = c_int::try_from(width)?;
+            let h = c_int::try_from(height)?;
+            let image = unsafe { DP_image16_new(w, h) };
+            Ok(Self { image })
+        } else {
+            Err(anyhow!("Empty image"))
+        }
+    }
+
+    pub fn new_from_pixels(width: usize, height: usize, pixels: &[u64]) -> Result<Self> {
+        let count = width * height;
+        if pixels.len() >= count {
+            let img = Self::new(width, height)?;
+            unsafe {
+                copy_nonoverlapping(
+                    pixels.as_ptr(),
+                    DP_image16_pixels(img.image).cast::<u64>(),
+                    count,
+                );
+            }
+            Ok(img)
+        } else {
+            Err(anyhow!("Not enough pixels"))
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        unsafe { DP_image16_width(self.image) as usize }
+    }
+
+    pub fn height(&self) -> usize {
+        unsafe { DP_image16_height(self.image) as usize }
+    }
+
+    pub fn pixels(&self) -> &[u64] {
+        unsafe {
+            slice::from_raw_parts(
+                DP_image16_pixels(self.image).cast(),
+                self.width() * self.height(),
+            )
+        }
+    }
+
+    pub fn blend_with(&mut self, src: &Image16, color: DP_UPixel16, opacity: u16) -> Result<()> {
+        let w = self.width();
+        let h = self.height();
+        if w != src.width() || h != src.height() {
+            return Err(anyhow!("Mismatched dimensions"));
+        }
+
+        unsafe {
+            DP_blend_color16_to(
+                DP_image16_pixels(self.image),
+                DP_image16_pixels(src.image),
+                color,
+                (w * h) as c_int,
+                opacity,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn add_background(&mut self, color: u64) {
+        let color = DP_UPixel16 { color };
+        unsafe {
+            DP_blend_color16_background(
+                DP_image16_pixels(self.image),
+                color,
+                (self.width() * self.height()) as c_int,
+            );
+        }
+    }
+
+    pub fn to_image8(&self) -> Result<Image> {
+        let image = unsafe { DP_image16_to_image8(self.image) };
+        if image.is_null() {
+            Err(dp_error_anyhow())
+        } else {
+            Ok(Image { image })
+        }
+    }
+
+    pub fn from_image8(image: &Image) -> Result<Self> {
+        let image = unsafe { DP_image16_new_from_image8(image.image) };
+        if image.is_null() {
+            Err(dp_error_anyhow())
+        } else {
+            Ok(Self { image })
+        }
+    }
+}
+
+impl Drop for Image16 {
+    fn drop(&mut self) {
+        unsafe { DP_image16_free(self.image) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Decoder = unsafe extern "C" fn(*mut DP_Input) -> *mut DP_Image;
+
+    #[test]
+    fn sniff_identifies_png() {
+        assert_eq!(
+            Image::sniff(b"\x89PNG\r\n\x1a\nrest"),
+            Some(DP_image_new_from_png as Decoder)
+        );
+    }
+
+    #[test]
+    fn sniff_identifies_qoi() {
+        assert_eq!(
+            Image::sniff(b"qoif rest"),
+            Some(DP_image_new_from_qoi as Decoder)
+        );
+    }
+
+    #[test]
+    fn sniff_identifies_webp() {
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(b"WEBP");
+        assert_eq!(
+            Image::sniff(&bytes),
+            Some(DP_image_new_from_webp as Decoder)
+        );
+    }
+
+    #[test]
+    fn sniff_identifies_jpeg() {
+        assert_eq!(
+            Image::sniff(&[0xff, 0xd8, 0xff, 0xe0]),
+            Some(DP_image_new_from_jpeg as Decoder)
+        );
+    }
+
+    #[test]
+    fn sniff_rejects_short_or_unrecognized_buffers() {
+        assert_eq!(Image::sniff(b""), None);
+        assert_eq!(Image::sniff(b"hi"), None);
+        assert_eq!(Image::sniff(b"not an image"), None);
+    }
+
+    #[test]
+    fn decode_rejects_unrecognized_buffer() {
+        assert!(Image::decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn find_bitmap_locates_needle_at_known_origin() {
+        #[rustfmt::skip]
+        let haystack = Image::new_from_pixels(4, 4, &[
+            0xff000000, 0xff000000, 0xff000000, 0xff000000,
+            0xff000000, 0xffaabbcc, 0xffaabbcc, 0xff000000,
+            0xff000000, 0xffaabbcc, 0xffaabbcc, 0xff000000,
+            0xff000000, 0xff000000, 0xff000000, 0xff000000,
+        ])
+        .unwrap();
+        let needle =
+            Image::new_from_pixels(2, 2, &[0xffaabbcc, 0xffaabbcc, 0xffaabbcc, 0xffaabbcc])
+                .unwrap();
+        assert_eq!(haystack.find_bitmap(&needle, 0.0), Some((1, 1)));
+        assert_eq!(haystack.count_of_bitmap(&needle, 0.0), 1);
+    }
+
+    #[test]
+    fn find_bitmap_matches_when_needle_equals_haystack_size() {
+        let image = Image::new_from_pixels(2, 2, &[1, 2, 3, 4]).unwrap();
+        let needle = Image::new_from_pixels(2, 2, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(image.find_bitmap(&needle, 0.0), Some((0, 0)));
+    }
+
+    #[test]
+    fn find_bitmap_returns_none_when_needle_larger_than_haystack() {
+        let haystack = Image::new_from_pixels(2, 2, &[0; 4]).unwrap();
+        let needle = Image::new_from_pixels(3, 3, &[0; 9]).unwrap();
+        assert_eq!(haystack.find_bitmap(&needle, 0.0), None);
+        assert_eq!(haystack.count_of_bitmap(&needle, 0.0), 0);
+    }
+
+    #[test]
+    fn bitmap_eq_respects_tolerance() {
+        let a = Image::new_from_pixels(1, 1, &[0x00102030]).unwrap();
+        let b = Image::new_from_pixels(1, 1, &[0x00112131]).unwrap();
+        assert!(!a.bitmap_eq(&b, 0.0));
+        assert!(a.bitmap_eq(&b, 0.02));
+    }
+
+    #[test]
+    fn get_pixel_returns_err_out_of_bounds() {
+        let image = Image::new_from_pixels(2, 2, &[1, 2, 3, 4]).unwrap();
+        assert!(image.get_pixel(2, 0).is_err());
+        assert!(image.get_pixel(0, 2).is_err());
+    }
+
+    #[test]
+    fn get_pixel_returns_the_stored_value_in_bounds() {
+        let image = Image::new_from_pixels(2, 2, &[1, 2, 3, 4]).unwrap();
+        let pixel = image.get_pixel(1, 1).unwrap();
+        assert_eq!(unsafe { pixel.color }, 4);
+    }
+
+    #[test]
+    fn set_pixel_returns_err_out_of_bounds() {
+        let mut image = Image::new_from_pixels(2, 2, &[1, 2, 3, 4]).unwrap();
+        assert!(image.set_pixel(2, 0, DP_UPixel8 { color: 9 }).is_err());
+        assert!(image.set_pixel(0, 2, DP_UPixel8 { color: 9 }).is_err());
+    }
+
+    #[test]
+    fn set_pixel_writes_the_value_in_bounds() {
+        let mut image = Image::new_from_pixels(2, 2, &[1, 2, 3, 4]).unwrap();
+        image.set_pixel(1, 0, DP_UPixel8 { color: 42 }).unwrap();
+        assert_eq!(unsafe { image.get_pixel(1, 0).unwrap().color }, 42);
+    }
+
+    #[test]
+    fn to_grayscale_uses_luminosity_weights() {
+        let mut image =
+            Image::new_from_pixels(1, 1, &[u32::from_ne_bytes([10, 20, 30, 255])]).unwrap();
+        image.to_grayscale();
+        let [r, g, b, a] = unsafe { image.get_pixel(0, 0).unwrap().color }.to_ne_bytes();
+        let expected = (0.21 * 10.0 + 0.72 * 20.0 + 0.07 * 30.0).round() as u8;
+        assert_eq!((r, g, b, a), (expected, expected, expected, 255));
+    }
+
+    #[test]
+    fn write_stream_callback_forwards_bytes_to_writer() {
+        let mut buf: Vec<u8> = Vec::new();
+        let mut writer: &mut dyn io::Write = &mut buf;
+        let context = (&mut writer as *mut &mut dyn io::Write).cast::<c_void>();
+        let data = b"hello";
+        let ok = unsafe { write_stream_callback(context, data.as_ptr().cast(), data.len()) };
+        assert!(ok);
+        assert_eq!(buf, b"hello");
+    }
+
+    #[test]
+    fn new_from_canvas_state_region_rejects_oversized_dimensions() {
+        let result = Image::new_from_canvas_state_region(std::ptr::null_mut(), 0, 0, usize::MAX, 1);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn image16_new_rejects_empty_dimensions() {
+        assert!(Image16::new(0, 4).is_err());
+        assert!(Image16::new(4, 0).is_err());
+    }
+
+    #[test]
+    fn image16_new_from_pixels_rejects_insufficient_pixels() {
+        let result = Image16::new_from_pixels(2, 2, &[1, 2, 3]);
+        assert!(result.is_err());
+    }
+}